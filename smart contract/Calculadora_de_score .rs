@@ -3,11 +3,66 @@ extern crate alloc;
 
 use alloc::vec::Vec;
 use stylus_sdk::{
-    alloy_primitives::{Address, Bytes, U256},
+    alloy_primitives::{Address, Bytes, I256, U256},
+    alloy_sol_types::sol,
+    msg,
     prelude::*,
-    storage::StorageAddress,
+    storage::{StorageAddress, StorageMap, StorageOwner},
 };
 
+// --- Errores tipados del contrato ---
+// Le permiten al llamador distinguir división por cero, dueño incorrecto
+// y fallas del call externo a UserAttestations.
+sol! {
+    error NotOwner();
+    error DivideByZero();
+    error ExternalCallFailed();
+}
+
+#[derive(SolidityError, Debug)]
+pub enum ContractError {
+    NotOwner(NotOwner),
+    DivideByZero(DivideByZero),
+    ExternalCallFailed(ExternalCallFailed),
+}
+
+/// Magnitud máxima representable en `I256` (2^255 - 1). Usada para acotar
+/// una conversión `U256 -> I256` en vez de reinterpretar los bytes crudos,
+/// lo que volteraría el signo en silencio si el valor no entra.
+const I256_MAX_MAGNITUDE: U256 = U256::from_limbs([
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+    0x7FFFFFFFFFFFFFFF,
+]);
+
+/// Aritmética de punto fijo de 18 decimales (WAD), usada para que el
+/// registro de pesos y el factor PPA admitan fracciones exactas (0.6,
+/// 1.2, ...) en vez de enteros truncados.
+mod wad_math {
+    use stylus_sdk::alloy_primitives::{U256, U512};
+
+    /// 1.0 representado en WAD.
+    pub const WAD: U256 = U256::from_limbs([1_000_000_000_000_000_000u64, 0, 0, 0]);
+
+    /// Calcula `a * b / denom` sobre un intermediate de 512 bits (para no
+    /// desbordar al multiplicar dos cantidades WAD) y redondea al más
+    /// cercano (half-up). Devuelve `None` si `denom` es cero o si el
+    /// resultado no cabe de vuelta en 256 bits.
+    pub fn mul_div(a: U256, b: U256, denom: U256) -> Option<U256> {
+        if denom.is_zero() {
+            return None;
+        }
+
+        let product = U512::from(a) * U512::from(b);
+        let denom_wide = U512::from(denom);
+        let half_denom = denom_wide / U512::from(2);
+
+        let rounded = (product + half_denom) / denom_wide;
+        U256::try_from(rounded).ok()
+    }
+}
+
 // --- Definición del Struct (debe coincidir con el de UserAttestations) ---
 #[derive(Default, Debug, EthAbiType, EthAbiCodec, Clone)]
 pub struct Attestation {
@@ -30,15 +85,24 @@ sol_interface! {
 pub struct ScoreCalculator {
     /// Dirección del contrato UserAttestations
     attestations_contract: StorageAddress,
+
+    /// Dueño del contrato (quien puede gobernar los pesos de scoring)
+    owner: StorageOwner,
+
+    /// Registro de pesos por `data_type` de atestado, en WAD con signo.
+    /// Gobierna la fórmula de scoring on-chain en vez de recompilar el
+    /// contrato cada vez que cambia la política.
+    weights: StorageMap<Bytes, I256>,
 }
 
 // --- Lógica del Contrato ---
 #[external]
 impl ScoreCalculator {
     /// Constructor: se despliega con la dirección de UserAttestations
-    pub fn new(attestations_address: Address) -> Result<Self, Vec<u8>> {
+    pub fn new(attestations_address: Address) -> Result<Self, ContractError> {
         let mut contract = Self::default();
         contract.attestations_contract.set(attestations_address);
+        contract.owner.set(msg::sender());
         Ok(contract)
     }
 
@@ -47,69 +111,132 @@ impl ScoreCalculator {
     pub fn calculate_score(
         &self,
         user_address: Address,
-        ppa_factor: U256, // El frontend pasa este valor
-    ) -> Result<U256, Vec<u8>> {
-        
+        ppa_factor_wad: U256, // El PPA en WAD (1.0 = WAD, 0.6 = 0.6 * WAD)
+    ) -> Result<U256, ContractError> {
+        if ppa_factor_wad.is_zero() {
+            return Err(ContractError::DivideByZero(DivideByZero {}));
+        }
+
         // --- Paso 1: Obtener Atestados ---
         let attestations_loader = IAttestations::new(self.attestations_contract.get());
-        let attestations = attestations_loader.get_attestations(self, user_address)?;
+        let attestations = attestations_loader
+            .get_attestations(self, user_address)
+            .map_err(|_| ContractError::ExternalCallFailed(ExternalCallFailed {}))?;
 
         // --- Paso 2: Iterar y Calcular Score Bruto ---
-        // ¡Aquí es donde pones tu fórmula de "weighted average"!
-        // Esto es solo un ejemplo.
-        let mut score_bruto = U256::from(0);
+        // Cada data_type pesa según el registro on-chain `weights`
+        // (gobernado por `set_weight`), no una fórmula harcodeada.
+        let mut weighted_sum = I256::ZERO;
 
         for att in attestations.iter() {
-            // Ejemplo de lógica simple basada en el data_type
-            if att.data_type == "INGRESO_ALTO".as_bytes() {
-                // Suma el valor (ej. 100 puntos)
-                score_bruto += att.value; 
-            } else if att.data_type == "NIVEL_DEUDA".as_bytes() {
-                // Resta el valor (ej. 50 puntos)
-                if score_bruto >= att.value {
-                    score_bruto -= att.value;
-                } else {
-                    score_bruto = U256::ZERO; // Evitar underflow
-                }
-            } else if att.data_type == "TASA_AHORRO_ALTA".as_bytes() {
-                score_bruto += att.value;
-            }
-            // ... agrega más lógica de negocio aquí
+            let weight = self.weights.get(att.data_type.clone());
+            weighted_sum = weighted_sum.saturating_add(Self::weighted_contribution(att.value, weight));
         }
 
-        // --- Paso 3: Aplicar PPA ---
-        // Evitar división por cero
-        if ppa_factor == U256::ZERO {
-            // Decide qué retornar en este caso, 0 tiene sentido.
-            return Ok(U256::ZERO); 
-        }
+        // Un score no puede ser negativo: lo acotamos a 0.
+        let score_bruto = if weighted_sum.is_negative() {
+            U256::ZERO
+        } else {
+            U256::from_be_bytes(weighted_sum.to_be_bytes::<32>())
+        };
 
-        // NOTA: U256 hace división de enteros. 
-        // Para manejar decimales (como 0.6), debes usar aritmética
-        // de punto fijo.
-        //
-        // Ejemplo: Si PPA=0.6, el frontend debe enviar 6 y un 
-        // factor de 10. (o 60 y factor 100).
-        //
-        // Asumamos que el frontend envía el PPA multiplicado por 100.
-        // Ej: Para 0.6, envía `ppa_factor = U256::from(60)`
-        // Ej: Para 1.2, envía `ppa_factor = U256::from(120)`
-        //
-        // final_score = (score_bruto * 100) / ppa_factor
-        
-        let precision_factor = U256::from(100);
-        
-        // Multiplicamos *primero* para preservar la precisión
-        let final_score = (score_bruto * precision_factor) / ppa_factor;
+        // --- Paso 3: Aplicar PPA (punto fijo WAD) ---
+        // final_score = score_bruto * WAD / ppa_factor_wad
+        // (ppa_factor_wad == 0 ya fue rechazado arriba con DivideByZero; aquí
+        // `None` solo ocurre si el resultado no cabe en 256 bits, y ese
+        // desborde también se reporta como DivideByZero)
+        let final_score = wad_math::mul_div(score_bruto, wad_math::WAD, ppa_factor_wad)
+            .ok_or(ContractError::DivideByZero(DivideByZero {}))?;
 
         // --- Paso 4: Devolver Score Final ---
         Ok(final_score)
     }
 
+    /// Aplica un peso con signo (en WAD) a un valor de atestado:
+    /// `contribution = sign(weight) * (value * |weight| / WAD)`.
+    fn weighted_contribution(value: U256, weight: I256) -> I256 {
+        let magnitude = weight.unsigned_abs();
+        let scaled = wad_math::mul_div(value, magnitude, wad_math::WAD).unwrap_or(U256::ZERO);
+
+        // Acotamos antes de reinterpretar como I256: un `scaled` >= 2^255
+        // volteraría el signo en silencio si lo leyéramos crudo de bytes.
+        let scaled_i256 = if scaled > I256_MAX_MAGNITUDE {
+            I256::MAX
+        } else {
+            I256::from_be_bytes(scaled.to_be_bytes::<32>())
+        };
+
+        // `scaled_i256` siempre queda en [0, I256::MAX], así que negarlo
+        // nunca puede desbordar (el único caso problemático es I256::MIN,
+        // que nunca se produce aquí).
+        if weight.is_negative() {
+            -scaled_i256
+        } else {
+            scaled_i256
+        }
+    }
+
+    /// Permite al dueño fijar el peso (en WAD, con signo) que un
+    /// `data_type` de atestado aporta al score. Gobierna la política de
+    /// scoring on-chain en vez de requerir recompilar el contrato.
+    pub fn set_weight(&mut self, data_type: Bytes, signed_weight: I256) -> Result<(), ContractError> {
+        self.owner
+            .guard()
+            .map_err(|_| ContractError::NotOwner(NotOwner {}))?;
+        self.weights.insert(data_type, signed_weight);
+        Ok(())
+    }
+
+    /// Lee el peso configurado para un `data_type` (0 si no fue fijado).
+    #[view]
+    pub fn get_weight(&self, data_type: Bytes) -> Result<I256, ContractError> {
+        Ok(self.weights.get(data_type))
+    }
+
     /// Permite al dueño actualizar la dirección del contrato de atestados
-    pub fn set_attestations_address(&mut self, new_address: Address) -> Result<(), Vec<u8>> {
-        // Proteger con 'onlyOwner' en producción
+    pub fn set_attestations_address(&mut self, new_address: Address) -> Result<(), ContractError> {
+        self.owner
+            .guard()
+            .map_err(|_| ContractError::NotOwner(NotOwner {}))?;
         self.attestations_contract.set(new_address);
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::wad_math;
+    use stylus_sdk::alloy_primitives::U256;
+
+    #[test]
+    fn mul_div_rounds_half_up() {
+        // 7 * 1 / 2 = 3.5 -> redondea a 4, no trunca a 3.
+        let result = wad_math::mul_div(U256::from(7u64), U256::from(1u64), U256::from(2u64));
+        assert_eq!(result, Some(U256::from(4u64)));
+    }
+
+    #[test]
+    fn mul_div_rounds_up_above_half() {
+        // 7 * 2 / 5 = 2.8 -> redondea a 3.
+        let result = wad_math::mul_div(U256::from(7u64), U256::from(2u64), U256::from(5u64));
+        assert_eq!(result, Some(U256::from(3u64)));
+    }
+
+    #[test]
+    fn mul_div_is_exact_when_it_divides_evenly() {
+        let result = wad_math::mul_div(wad_math::WAD, wad_math::WAD, wad_math::WAD);
+        assert_eq!(result, Some(wad_math::WAD));
+    }
+
+    #[test]
+    fn mul_div_rejects_zero_denominator() {
+        assert_eq!(wad_math::mul_div(U256::from(1u64), U256::from(1u64), U256::ZERO), None);
+    }
+
+    #[test]
+    fn mul_div_widens_through_u512_without_overflowing() {
+        // a * b excede U256::MAX si se calculara en 256 bits sin ensanchar.
+        let result = wad_math::mul_div(U256::MAX, U256::MAX, U256::MAX);
+        assert_eq!(result, Some(U256::MAX));
+    }
 }
\ No newline at end of file
@@ -1,168 +1,736 @@
-#![cfg_attr(not(feature = "std"), no_std)]
-extern crate alloc;
-
-use alloc::vec::Vec;
-use stylus_sdk::{
-    alloy_primitives::{Address, Bytes, U256},
-    prelude::*,
-    storage::StorageMap, 
-    block, 
-    msg,   
-};
-
-/// 5 años en segundos (5 * 365 * 24 * 60 * 60)
-/// Usamos U256 para la resta
-const FIVE_YEARS_IN_SECONDS: U256 = U256::from_limbs([157_680_000, 0, 0, 0]);
-
-// --- 1. Definición del Registro de Préstamo ---
-// Esta es la nueva "plantilla" para cada préstamo.
-#[derive(Default, Debug, EthAbiType, EthAbiCodec, Clone)]
-pub struct LoanRecord {
-    /// El banco que reporta el préstamo
-    provider: Address,
-    /// Cuándo se OTORGÓ el préstamo (para el filtro de 5 años)
-    timestamp_issued: U256,
-    /// Monto del préstamo (puede ser útil para el analista)
-    loan_amount: U256,
-    /// 'true' si el préstamo ya fue pagado
-    is_paid: bool,
-    /// Cuándo se CONSUMÓ el pago
-    timestamp_paid: U256, // Será 0 si 'is_paid' es 'false'
-}
-
-// --- 2. Almacenamiento del Contrato ---
-#[sol_storage]
-#[entrypoint]
-pub struct LoanComplianceLedger {
-    /// BASE DE DATOS: Address (usuario) => Lista [Vec] de sus préstamos
-    user_loans: StorageMap<Address, Vec<LoanRecord>>,
-}
-
-// --- 3. Lógica del Contrato ---
-#[external]
-impl LoanComplianceLedger {
-    
-    /// --- CONSTRUCTOR ---
-    pub fn new() -> Result<Self, Vec<u8>> {
-        Ok(Self::default())
-    }
-
-    /// --- FUNCIÓN DE ESCRITURA 1: REGISTRAR UN NUEVO PRÉSTAMO ---
-    /// Un banco llama a esto para registrar un préstamo que acaba de otorgar.
-    pub fn add_loan_record(
-        &mut self,
-        user_address: Address, // La wallet del cliente
-        loan_amount: U256,     // El monto que se le prestó
-    ) -> Result<(), Vec<u8>> {
-        
-        // Quien llama es el banco (msg.sender)
-        let provider_address = msg::sender(); 
-
-        let new_loan = LoanRecord {
-            provider: provider_address, 
-            timestamp_issued: block::timestamp(), // Se registra cuándo se OTORGÓ
-            loan_amount,
-            is_paid: false, // El préstamo inicia como NO pagado
-            timestamp_paid: U256::ZERO, // Aún no hay fecha de pago
-        };
-
-        let mut loan_list = self.user_loans.get(user_address);
-        loan_list.push(new_loan);
-        self.user_loans.insert(user_address, loan_list);
-
-        Ok(())
-    }
-
-    /// --- FUNCIÓN DE ESCRITURA 2: MARCAR UN PRÉSTAMO COMO PAGADO ---
-    /// El banco llama a esto cuando el cliente consuma el pago.
-    pub fn mark_loan_as_paid(
-        &mut self,
-        user_address: Address, // La wallet del cliente
-        loan_index: U256,      // El índice del préstamo en la lista
-    ) -> Result<(), Vec<u8>> {
-        
-        let bank_address = msg::sender();
-
-        // Obtenemos la lista de préstamos de forma mutable
-        let mut loan_list = self.user_loans.get_mut(user_address);
-        
-        // Convertimos el U256 a usize para usarlo como índice
-        let index = loan_index.to::<usize>();
-
-        // Verificamos que el índice exista en la lista
-        if let Some(loan) = loan_list.get_mut(index) {
-            
-            // ¡GUARDIA DE SEGURIDAD!
-            // Solo el banco que OTORGÓ el préstamo puede marcarlo como pagado.
-            if loan.provider != bank_address {
-                return Err(b"NOT_ORIGINAL_PROVIDER".to_vec());
-            }
-
-            // Verificamos que no esté ya pagado
-            if loan.is_paid {
-                return Err(b"LOAN_ALREADY_PAID".to_vec());
-            }
-
-            // Actualizamos el registro
-            loan.is_paid = true;
-            loan.timestamp_paid = block::timestamp(); // Esta es la "fecha de consumación"
-
-            // Guardamos la lista modificada
-            loan_list.save();
-            Ok(())
-
-        } else {
-            // Si el índice no existe
-            Err(b"LOAN_INDEX_OUT_OF_BOUNDS".to_RECT_vec())
-        }
-    }
-
-    /// --- FUNCIÓN DE LECTURA 1: OBTENER HISTORIAL BRUTO ---
-    /// Devuelve la lista completa de préstamos de un usuario.
-    #[view]
-    pub fn get_loan_history(&self, user_address: Address) -> Result<Vec<LoanRecord>, Vec<u8>> {
-        Ok(self.user_loans.get(user_address))
-    }
-
-    /// --- FUNCIÓN DE LECTURA 2: OBTENER PORCENTAJE DE CUMPLIMIENTO (ÚLTIMOS 5 AÑOS) ---
-    /// Esta es la función que llamaría el prestamista para analizar.
-    #[view]
-    pub fn get_compliance_percentage(&self, user_address: Address) -> Result<U256, Vec<u8>> {
-        
-        let mut total_loans_in_period = U256::ZERO;
-        let mut paid_loans_in_period = U256::ZERO;
-
-        // 1. Calcular el punto de corte (timestamp de hace 5 años)
-        let now = block::timestamp();
-        // Usamos saturating_sub para evitar underflow si la blockchain es muy nueva
-        let five_years_ago = now.saturating_sub(FIVE_YEARS_IN_SECONDS);
-
-        // 2. Obtener la lista de préstamos
-        let loan_list = self.user_loans.get(user_address);
-
-        // 3. Iterar y filtrar
-        for loan in loan_list.iter() {
-            // ¡FILTRO DE 5 AÑOS!
-            // Solo contamos préstamos OTORGADOS en los últimos 5 años
-            if loan.timestamp_issued >= five_years_ago {
-                total_loans_in_period += U256::from(1);
-
-                if loan.is_paid {
-                    paid_loans_in_period += U256::from(1);
-                }
-            }
-        }
-
-        // 4. Calcular porcentaje
-        if total_loans_in_period == U256::ZERO {
-            // Si no hay préstamos en los últimos 5 años, tiene 100% de cumplimiento
-            // (no ha fallado en ningún pago). Esto es debatible, pero es un default seguro.
-            return Ok(U256::from(100));
-        }
-
-        // Usamos multiplicación primero para preservar la precisión con enteros
-        let percentage = (paid_loans_in_period * U256::from(100)) / total_loans_in_period;
-
-        Ok(percentage)
-    }
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+use alloc::vec::Vec;
+use stylus_sdk::{
+    alloy_primitives::{Address, B256, Bytes, U256, U512},
+    alloy_sol_types::sol,
+    call::RawCall,
+    crypto::keccak,
+    prelude::*,
+    storage::{StorageAddress, StorageMap},
+    block,
+    contract,
+    msg,
+};
+
+// --- Definición de la Interfaz para BankRegistry ---
+sol_interface! {
+    interface IBankRegistry {
+        function is_trusted_bank(address bank_address) external view returns (bool);
+    }
+}
+
+// --- Errores tipados del contrato ---
+// Cubren cada motivo de revert de LoanComplianceLedger (whitelist, firma
+// off-chain, índices e idempotencia de pagos) con un selector propio.
+sol! {
+    error UntrustedProvider();
+    error LoanAlreadyPaid();
+    error IndexOutOfBounds();
+    error NotOriginalProvider();
+    error StaleNonce();
+    error SignerMismatch();
+    error InvalidSignatureLength();
+    error EcrecoverCallFailed();
+    error ExternalCallFailed();
+}
+
+#[derive(SolidityError, Debug)]
+pub enum ContractError {
+    UntrustedProvider(UntrustedProvider),
+    LoanAlreadyPaid(LoanAlreadyPaid),
+    IndexOutOfBounds(IndexOutOfBounds),
+    NotOriginalProvider(NotOriginalProvider),
+    StaleNonce(StaleNonce),
+    SignerMismatch(SignerMismatch),
+    InvalidSignatureLength(InvalidSignatureLength),
+    EcrecoverCallFailed(EcrecoverCallFailed),
+    ExternalCallFailed(ExternalCallFailed),
+}
+
+/// 5 años en segundos (5 * 365 * 24 * 60 * 60)
+/// Usamos U256 para la resta
+const FIVE_YEARS_IN_SECONDS: U256 = U256::from_limbs([157_680_000, 0, 0, 0]);
+
+/// 1 año en segundos, usado para anualizar `interest_rate_bps`.
+const SECONDS_PER_YEAR: U256 = U256::from_limbs([31_536_000, 0, 0, 0]);
+
+/// Denominador de basis points (100 bps = 1%).
+const BPS_DENOMINATOR: U256 = U256::from_limbs([10_000, 0, 0, 0]);
+
+/// Tope de página para `get_loan_history_paged`: ningún caller puede
+/// forzar una respuesta más grande que esto, sin importar el `limit` pedido.
+const MAX_PAGE_SIZE: usize = 100;
+
+// --- 1. Definición del Registro de Préstamo ---
+// Esta es la nueva "plantilla" para cada préstamo.
+#[derive(Default, Debug, EthAbiType, EthAbiCodec, Clone)]
+pub struct LoanRecord {
+    /// El banco que reporta el préstamo
+    provider: Address,
+    /// Cuándo se OTORGÓ el préstamo (para el filtro de 5 años)
+    timestamp_issued: U256,
+    /// Monto del préstamo (puede ser útil para el analista)
+    loan_amount: U256,
+    /// 'true' si el préstamo ya fue pagado
+    is_paid: bool,
+    /// Cuándo se CONSUMÓ el pago
+    timestamp_paid: U256, // Será 0 si 'is_paid' es 'false'
+    /// Epoch de renta (estilo Solana rent-collector), derivado de
+    /// `timestamp_issued`. Es solo un índice para indexadores off-chain:
+    /// `collect_rent` decide la poda por `timestamp_issued`, no por este campo.
+    rent_epoch: U256,
+    /// Tasa de interés anual en basis points (100 bps = 1%)
+    interest_rate_bps: U256,
+    /// Colateral depositado respaldando el préstamo
+    collateral_amount: U256,
+    /// Cuándo vence el préstamo (principal + interés deben estar pagados)
+    due_timestamp: U256,
+    /// Cuánto del principal + interés acumulado se ha repagado hasta ahora
+    amount_repaid: U256,
+}
+
+// --- 2. Almacenamiento del Contrato ---
+#[sol_storage]
+#[entrypoint]
+pub struct LoanComplianceLedger {
+    /// BASE DE DATOS: Address (usuario) => Lista [Vec] de sus préstamos
+    user_loans: StorageMap<Address, Vec<LoanRecord>>,
+
+    /// Nonce esperado del PRÓXIMO reporte firmado de cada banco, para
+    /// evitar que un relayer reenvíe (replay) una firma ya consumida.
+    provider_nonces: StorageMap<Address, U256>,
+
+    /// Dirección del contrato BankRegistry, única autoridad sobre quién
+    /// puede reportar préstamos.
+    bank_registry: StorageAddress,
+}
+
+// --- 3. Lógica del Contrato ---
+#[external]
+impl LoanComplianceLedger {
+    
+    /// --- CONSTRUCTOR ---
+    /// Se despliega con la dirección de BankRegistry, la única autoridad
+    /// sobre qué bancos pueden reportar préstamos.
+    pub fn new(bank_registry: Address) -> Result<Self, ContractError> {
+        let mut contract = Self::default();
+        contract.bank_registry.set(bank_registry);
+        Ok(contract)
+    }
+
+    /// Revierte con `UntrustedProvider` a menos que `bank_address` esté
+    /// en la whitelist de BankRegistry.
+    fn require_trusted_bank(&self, bank_address: Address) -> Result<(), ContractError> {
+        let registry = IBankRegistry::new(self.bank_registry.get());
+        let is_trusted = registry
+            .is_trusted_bank(self, bank_address)
+            .map_err(|_| ContractError::ExternalCallFailed(ExternalCallFailed {}))?;
+        if !is_trusted {
+            return Err(ContractError::UntrustedProvider(UntrustedProvider {}));
+        }
+        Ok(())
+    }
+
+    /// --- FUNCIÓN DE ESCRITURA 1: REGISTRAR UN NUEVO PRÉSTAMO ---
+    /// Un banco llama a esto para registrar un préstamo que acaba de otorgar.
+    pub fn add_loan_record(
+        &mut self,
+        user_address: Address,      // La wallet del cliente
+        loan_amount: U256,          // El monto que se le prestó
+        interest_rate_bps: U256,    // Tasa de interés anual en basis points
+        collateral_amount: U256,    // Colateral respaldando el préstamo
+        due_timestamp: U256,        // Cuándo vence el préstamo
+    ) -> Result<(), ContractError> {
+
+        // Quien llama es el banco (msg.sender)
+        let provider_address = msg::sender();
+        self.require_trusted_bank(provider_address)?;
+
+        let timestamp_issued = block::timestamp(); // Se registra cuándo se OTORGÓ
+
+        let new_loan = LoanRecord {
+            provider: provider_address,
+            timestamp_issued,
+            loan_amount,
+            is_paid: false, // El préstamo inicia como NO pagado
+            timestamp_paid: U256::ZERO, // Aún no hay fecha de pago
+            rent_epoch: Self::rent_epoch_for(timestamp_issued),
+            interest_rate_bps,
+            collateral_amount,
+            due_timestamp,
+            amount_repaid: U256::ZERO,
+        };
+
+        let mut loan_list = self.user_loans.get(user_address);
+        loan_list.push(new_loan);
+        self.user_loans.insert(user_address, loan_list);
+
+        Ok(())
+    }
+
+    /// --- FUNCIÓN DE ESCRITURA 1B: REGISTRAR PRÉSTAMO CON FIRMA OFF-CHAIN ---
+    /// Permite que un relayer envíe en batch préstamos que el banco firmó
+    /// off-chain (sobre el keccak256 crudo de `loan_struct_hash`, sin prefijo
+    /// EIP-191), en vez de que cada banco pague su
+    /// propia transacción. El firmante recuperado vía `ecrecover` debe
+    /// coincidir con `provider`. El banco debe firmar sobre los 10 campos
+    /// que ve `loan_struct_hash` (incluyendo `interest_rate_bps`,
+    /// `collateral_amount` y `due_timestamp`): un relayer no puede colar
+    /// términos de préstamo distintos a los que el banco aprobó.
+    pub fn add_loan_record_signed(
+        &mut self,
+        provider: Address,
+        user_address: Address,
+        loan_amount: U256,
+        timestamp_issued: U256,
+        nonce: U256,
+        signature: Bytes,
+        interest_rate_bps: U256,
+        collateral_amount: U256,
+        due_timestamp: U256,
+    ) -> Result<(), ContractError> {
+        // El nonce enviado debe ser exactamente el siguiente esperado para
+        // este banco (protege contra replay y contra reordenamiento).
+        let expected_nonce = self.provider_nonces.get(provider);
+        if nonce != expected_nonce {
+            return Err(ContractError::StaleNonce(StaleNonce {}));
+        }
+
+        // Atamos la firma a esta cadena concreta (estilo EIP-155): una
+        // firma válida en un fork o en un deployment hermano no sirve aquí.
+        let chain_id = U256::from(block::chainid());
+
+        let struct_hash = Self::loan_struct_hash(
+            provider,
+            user_address,
+            loan_amount,
+            timestamp_issued,
+            nonce,
+            chain_id,
+            contract::address(),
+            interest_rate_bps,
+            collateral_amount,
+            due_timestamp,
+        );
+
+        let recovered = Self::ecrecover(struct_hash, &signature)?;
+        if recovered != provider {
+            return Err(ContractError::SignerMismatch(SignerMismatch {}));
+        }
+        self.require_trusted_bank(provider)?;
+
+        self.provider_nonces.insert(provider, nonce + U256::from(1));
+
+        let new_loan = LoanRecord {
+            provider,
+            timestamp_issued,
+            loan_amount,
+            is_paid: false,
+            timestamp_paid: U256::ZERO,
+            rent_epoch: Self::rent_epoch_for(timestamp_issued),
+            interest_rate_bps,
+            collateral_amount,
+            due_timestamp,
+            amount_repaid: U256::ZERO,
+        };
+
+        let mut loan_list = self.user_loans.get(user_address);
+        loan_list.push(new_loan);
+        self.user_loans.insert(user_address, loan_list);
+
+        Ok(())
+    }
+
+    /// Epoch de renta de un préstamo, derivado de su `timestamp_issued`.
+    fn rent_epoch_for(timestamp_issued: U256) -> U256 {
+        timestamp_issued / FIVE_YEARS_IN_SECONDS
+    }
+
+    /// Interés acumulado linealmente entre `timestamp_issued` y `now`,
+    /// sobre `loan_amount` a `interest_rate_bps` anual.
+    fn accrued_interest(
+        loan_amount: U256,
+        interest_rate_bps: U256,
+        timestamp_issued: U256,
+        now: U256,
+    ) -> U256 {
+        let elapsed = now.saturating_sub(timestamp_issued);
+        // `interest_rate_bps` lo fija el banco emisor y un préstamo puede
+        // vivir años: encadenar tres multiplicaciones en U256 (como antes)
+        // puede desbordar y envolver en silencio. Igual que `wad_math::mul_div`,
+        // ensanchamos el producto a 512 bits antes de dividir.
+        let numerator = U512::from(loan_amount) * U512::from(interest_rate_bps) * U512::from(elapsed);
+        let denominator = U512::from(BPS_DENOMINATOR) * U512::from(SECONDS_PER_YEAR);
+        U256::try_from(numerator / denominator).unwrap_or(U256::MAX)
+    }
+
+    /// Principal más interés acumulado que un préstamo adeuda a `now`.
+    fn total_owed(loan: &LoanRecord, now: U256) -> U256 {
+        let accrued = Self::accrued_interest(
+            loan.loan_amount,
+            loan.interest_rate_bps,
+            loan.timestamp_issued,
+            now,
+        );
+        loan.loan_amount + accrued
+    }
+
+    /// Construye el keccak256 de
+    /// `(provider, user_address, loan_amount, timestamp_issued, nonce, chain_id, address(this), interest_rate_bps, collateral_amount, due_timestamp)`,
+    /// el mismo mensaje que el banco debe firmar off-chain. Los términos del
+    /// préstamo (tasa, colateral, vencimiento) están atados a la firma igual
+    /// que el resto de los campos: un relayer no puede sustituirlos sin
+    /// invalidar la firma del banco.
+    fn loan_struct_hash(
+        provider: Address,
+        user_address: Address,
+        loan_amount: U256,
+        timestamp_issued: U256,
+        nonce: U256,
+        chain_id: U256,
+        this: Address,
+        interest_rate_bps: U256,
+        collateral_amount: U256,
+        due_timestamp: U256,
+    ) -> B256 {
+        let mut preimage = Vec::with_capacity(32 * 10);
+        preimage.extend_from_slice(&[0u8; 12]);
+        preimage.extend_from_slice(provider.as_slice());
+        preimage.extend_from_slice(&[0u8; 12]);
+        preimage.extend_from_slice(user_address.as_slice());
+        preimage.extend_from_slice(&loan_amount.to_be_bytes::<32>());
+        preimage.extend_from_slice(&timestamp_issued.to_be_bytes::<32>());
+        preimage.extend_from_slice(&nonce.to_be_bytes::<32>());
+        preimage.extend_from_slice(&chain_id.to_be_bytes::<32>());
+        preimage.extend_from_slice(&[0u8; 12]);
+        preimage.extend_from_slice(this.as_slice());
+        preimage.extend_from_slice(&interest_rate_bps.to_be_bytes::<32>());
+        preimage.extend_from_slice(&collateral_amount.to_be_bytes::<32>());
+        preimage.extend_from_slice(&due_timestamp.to_be_bytes::<32>());
+
+        keccak(preimage)
+    }
+
+    /// Recupera la dirección que produjo `signature` (r ‖ s ‖ v, 65 bytes)
+    /// sobre `hash`, delegando en el precompile `ecrecover` (0x01).
+    fn ecrecover(hash: B256, signature: &[u8]) -> Result<Address, ContractError> {
+        if signature.len() != 65 {
+            return Err(ContractError::InvalidSignatureLength(InvalidSignatureLength {}));
+        }
+
+        let r = &signature[0..32];
+        let s = &signature[32..64];
+        let v = signature[64];
+
+        let mut input = [0u8; 128];
+        input[0..32].copy_from_slice(hash.as_slice());
+        input[63] = v;
+        input[64..96].copy_from_slice(r);
+        input[96..128].copy_from_slice(s);
+
+        let output = RawCall::new_static()
+            .call(Address::with_last_byte(1), &input)
+            .map_err(|_| ContractError::EcrecoverCallFailed(EcrecoverCallFailed {}))?;
+
+        if output.len() < 32 {
+            return Err(ContractError::EcrecoverCallFailed(EcrecoverCallFailed {}));
+        }
+        Ok(Address::from_slice(&output[12..32]))
+    }
+
+    /// --- FUNCIÓN DE ESCRITURA 2: MARCAR UN PRÉSTAMO COMO PAGADO ---
+    /// El banco llama a esto cuando el cliente consuma el pago.
+    pub fn mark_loan_as_paid(
+        &mut self,
+        user_address: Address, // La wallet del cliente
+        loan_index: U256,      // El índice del préstamo en la lista
+    ) -> Result<(), ContractError> {
+
+        let bank_address = msg::sender();
+        self.require_trusted_bank(bank_address)?;
+
+        // Obtenemos la lista de préstamos de forma mutable
+        let mut loan_list = self.user_loans.get_mut(user_address);
+
+        // Convertimos el U256 a usize para usarlo como índice
+        let index = loan_index.to::<usize>();
+
+        // Verificamos que el índice exista en la lista
+        if let Some(loan) = loan_list.get_mut(index) {
+
+            // ¡GUARDIA DE SEGURIDAD!
+            // Solo el banco que OTORGÓ el préstamo puede marcarlo como pagado.
+            if loan.provider != bank_address {
+                return Err(ContractError::NotOriginalProvider(NotOriginalProvider {}));
+            }
+
+            // Verificamos que no esté ya pagado
+            if loan.is_paid {
+                return Err(ContractError::LoanAlreadyPaid(LoanAlreadyPaid {}));
+            }
+
+            // Actualizamos el registro
+            loan.is_paid = true;
+            loan.timestamp_paid = block::timestamp(); // Esta es la "fecha de consumación"
+
+            // Guardamos la lista modificada
+            loan_list.save();
+            Ok(())
+
+        } else {
+            // Si el índice no existe
+            Err(ContractError::IndexOutOfBounds(IndexOutOfBounds {}))
+        }
+    }
+
+    /// --- FUNCIÓN DE ESCRITURA 3: REPAGO (PARCIAL O TOTAL) DE UN PRÉSTAMO ---
+    /// Solo el banco que otorgó el préstamo puede asentar un repago (misma
+    /// guardia que `mark_loan_as_paid`): acreditar `amount_repaid` es una
+    /// afirmación de que el valor ya fue custodiado off-chain/en el banco,
+    /// así que no puede dejarse abierta a cualquier dirección. Acumula
+    /// `amount` sobre lo ya repagado y marca el préstamo como pagado en
+    /// cuanto cubre principal + interés acumulado.
+    pub fn repay_loan(
+        &mut self,
+        user_address: Address,
+        loan_index: U256,
+        amount: U256,
+    ) -> Result<(), ContractError> {
+        let bank_address = msg::sender();
+        self.require_trusted_bank(bank_address)?;
+
+        let mut loan_list = self.user_loans.get_mut(user_address);
+        let index = loan_index.to::<usize>();
+
+        if let Some(loan) = loan_list.get_mut(index) {
+            if loan.provider != bank_address {
+                return Err(ContractError::NotOriginalProvider(NotOriginalProvider {}));
+            }
+
+            if loan.is_paid {
+                return Err(ContractError::LoanAlreadyPaid(LoanAlreadyPaid {}));
+            }
+
+            let now = block::timestamp();
+            let owed = Self::total_owed(loan, now);
+
+            loan.amount_repaid += amount;
+
+            if loan.amount_repaid >= owed {
+                loan.is_paid = true;
+                loan.timestamp_paid = now;
+            }
+
+            loan_list.save();
+            Ok(())
+        } else {
+            Err(ContractError::IndexOutOfBounds(IndexOutOfBounds {}))
+        }
+    }
+
+    /// --- FUNCIÓN DE LECTURA: ¿ESTÁ EN MORA? ---
+    /// `true` si ya pasó `due_timestamp` y lo repagado todavía no cubre
+    /// principal + interés acumulado.
+    #[view]
+    pub fn is_delinquent(&self, user_address: Address, loan_index: U256) -> Result<bool, ContractError> {
+        let loan_list = self.user_loans.get(user_address);
+        let index = loan_index.to::<usize>();
+
+        let loan = loan_list
+            .get(index)
+            .ok_or_else(|| ContractError::IndexOutOfBounds(IndexOutOfBounds {}))?;
+
+        let now = block::timestamp();
+        let owed = Self::total_owed(loan, now);
+
+        Ok(now > loan.due_timestamp && loan.amount_repaid < owed)
+    }
+
+    /// --- FUNCIÓN DE LECTURA 1: OBTENER HISTORIAL BRUTO ---
+    /// Devuelve la lista completa de préstamos de un usuario.
+    #[view]
+    pub fn get_loan_history(&self, user_address: Address) -> Result<Vec<LoanRecord>, ContractError> {
+        Ok(self.user_loans.get(user_address))
+    }
+
+    /// --- FUNCIÓN DE LECTURA: CANTIDAD DE PRÉSTAMOS DE UN USUARIO ---
+    #[view]
+    pub fn get_loan_count(&self, user_address: Address) -> Result<U256, ContractError> {
+        Ok(U256::from(self.user_loans.get(user_address).len()))
+    }
+
+    /// --- FUNCIÓN DE LECTURA: HISTORIAL PAGINADO ---
+    /// Devuelve como máximo `MAX_PAGE_SIZE` registros a partir de `offset`,
+    /// junto con la cantidad realmente devuelta, para que el caller pueda
+    /// iterar de forma determinística sin cargar todo el historial.
+    #[view]
+    pub fn get_loan_history_paged(
+        &self,
+        user_address: Address,
+        offset: U256,
+        limit: U256,
+    ) -> Result<(Vec<LoanRecord>, U256), ContractError> {
+        let loan_list = self.user_loans.get(user_address);
+        let len = U256::from(loan_list.len());
+
+        let start = offset.min(len).to::<usize>();
+        let requested = limit.min(U256::from(MAX_PAGE_SIZE)).to::<usize>();
+        let end = (start + requested).min(loan_list.len());
+
+        let page = loan_list[start..end].to_vec();
+        let returned_count = U256::from(page.len());
+
+        Ok((page, returned_count))
+    }
+
+    /// --- FUNCIÓN DE LECTURA 2: OBTENER PORCENTAJE DE CUMPLIMIENTO (ÚLTIMOS 5 AÑOS) ---
+    /// Esta es la función que llamaría el prestamista para analizar.
+    #[view]
+    pub fn get_compliance_percentage(&self, user_address: Address) -> Result<U256, ContractError> {
+        // Usamos saturating_sub para evitar underflow si la blockchain es muy nueva
+        let now = block::timestamp();
+        let five_years_ago = now.saturating_sub(FIVE_YEARS_IN_SECONDS);
+
+        self.get_compliance_percentage_range(user_address, five_years_ago, now)
+    }
+
+    /// --- FUNCIÓN DE LECTURA: PORCENTAJE DE CUMPLIMIENTO EN UNA VENTANA ARBITRARIA ---
+    /// Igual que `get_compliance_percentage`, pero sobre `[start_ts, end_ts]`
+    /// en vez de la ventana fija de 5 años, para que un analista pueda
+    /// consultar cualquier rango sin cargar todo el historial.
+    #[view]
+    pub fn get_compliance_percentage_range(
+        &self,
+        user_address: Address,
+        start_ts: U256,
+        end_ts: U256,
+    ) -> Result<U256, ContractError> {
+        let now = block::timestamp();
+
+        let mut total_loans_in_period = U256::ZERO;
+        let mut paid_loans_in_period = U256::ZERO;
+
+        let loan_list = self.user_loans.get(user_address);
+
+        for loan in loan_list.iter() {
+            // Solo contamos préstamos OTORGADOS dentro de la ventana pedida
+            if loan.timestamp_issued < start_ts || loan.timestamp_issued > end_ts {
+                continue;
+            }
+
+            if loan.is_paid {
+                // Pagado limpio: cuenta a favor.
+                total_loans_in_period += U256::from(1);
+                paid_loans_in_period += U256::from(1);
+            } else if now > loan.due_timestamp && loan.amount_repaid < Self::total_owed(loan, now) {
+                // En mora: cuenta en contra (no suma a paid_loans_in_period).
+                total_loans_in_period += U256::from(1);
+            }
+            // Si no está pagado pero todavía no vence, está "en curso" y
+            // aún no se juzga: no entra al denominador.
+        }
+
+        if total_loans_in_period == U256::ZERO {
+            // Si no hay préstamos juzgables en la ventana (ninguno pagado ni
+            // en mora; los "en curso" no cuentan), tiene 100% de cumplimiento
+            // (no ha fallado en ningún pago). Esto es debatible, pero es un default seguro.
+            return Ok(U256::from(100));
+        }
+
+        // Usamos multiplicación primero para preservar la precisión con enteros
+        let percentage = (paid_loans_in_period * U256::from(100)) / total_loans_in_period;
+
+        Ok(percentage)
+    }
+
+    /// --- FUNCIÓN DE ESCRITURA 3: RECOLECTAR RENTA (PODAR HISTORIAL VIEJO) ---
+    /// Inspirada en el rent-collector de Solana: cualquiera puede llamarla
+    /// para compactar el historial de un usuario, liberando los slots de
+    /// préstamos que ya fueron pagados y quedaron fuera de la ventana de
+    /// cumplimiento de 5 años. Nunca poda préstamos sin pagar ni préstamos
+    /// todavía dentro de la ventana, así que `get_compliance_percentage`
+    /// sigue siendo correcto después de podar. Devuelve cuántos registros
+    /// se recuperaron, para que un crane off-chain pueda ser incentivado
+    /// a llamarla.
+    pub fn collect_rent(&mut self, user_address: Address) -> Result<U256, ContractError> {
+        // `rent_epoch` solo sirve como índice barato para que un indexador
+        // off-chain sepa qué cuentas mirar primero; la poda en sí siempre
+        // valida el tiempo transcurrido real. Comparar por bucket de epoch
+        // podaría registros recién emitidos (hasta 5 años menos 1 segundo
+        // de antigüedad real) apenas cruzan un límite de epoch.
+        let now = block::timestamp();
+        let five_years_ago = now.saturating_sub(FIVE_YEARS_IN_SECONDS);
+
+        let loan_list = self.user_loans.get(user_address);
+
+        let mut kept = Vec::with_capacity(loan_list.len());
+        let mut reclaimed = U256::ZERO;
+
+        for loan in loan_list.iter() {
+            let prunable = loan.is_paid && loan.timestamp_issued < five_years_ago;
+            if prunable {
+                reclaimed += U256::from(1);
+            } else {
+                kept.push(loan.clone());
+            }
+        }
+
+        if reclaimed > U256::ZERO {
+            self.user_loans.insert(user_address, kept);
+        }
+
+        Ok(reclaimed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `loan_struct_hash` es lo único que el banco firma off-chain: si dos
+    /// preimages con un solo campo económico distinto produjeran el mismo
+    /// hash, un relayer podría sustituir esos términos sin invalidar la
+    /// firma (el bug que motivó atar `interest_rate_bps`, `collateral_amount`
+    /// y `due_timestamp` a la firma).
+    #[test]
+    fn loan_struct_hash_binds_loan_terms() {
+        let provider = Address::with_last_byte(1);
+        let user_address = Address::with_last_byte(2);
+        let this = Address::with_last_byte(3);
+
+        let base = LoanComplianceLedger::loan_struct_hash(
+            provider,
+            user_address,
+            U256::from(1_000u64),
+            U256::from(100u64),
+            U256::ZERO,
+            U256::from(1u64),
+            this,
+            U256::from(500u64),
+            U256::from(2_000u64),
+            U256::from(200u64),
+        );
+
+        let different_rate = LoanComplianceLedger::loan_struct_hash(
+            provider,
+            user_address,
+            U256::from(1_000u64),
+            U256::from(100u64),
+            U256::ZERO,
+            U256::from(1u64),
+            this,
+            U256::from(501u64), // interest_rate_bps cambia
+            U256::from(2_000u64),
+            U256::from(200u64),
+        );
+
+        let different_collateral = LoanComplianceLedger::loan_struct_hash(
+            provider,
+            user_address,
+            U256::from(1_000u64),
+            U256::from(100u64),
+            U256::ZERO,
+            U256::from(1u64),
+            this,
+            U256::from(500u64),
+            U256::from(2_001u64), // collateral_amount cambia
+            U256::from(200u64),
+        );
+
+        let different_due = LoanComplianceLedger::loan_struct_hash(
+            provider,
+            user_address,
+            U256::from(1_000u64),
+            U256::from(100u64),
+            U256::ZERO,
+            U256::from(1u64),
+            this,
+            U256::from(500u64),
+            U256::from(2_000u64),
+            U256::from(201u64), // due_timestamp cambia
+        );
+
+        assert_ne!(base, different_rate);
+        assert_ne!(base, different_collateral);
+        assert_ne!(base, different_due);
+
+        // Determinista: mismos campos, mismo hash.
+        let repeated = LoanComplianceLedger::loan_struct_hash(
+            provider,
+            user_address,
+            U256::from(1_000u64),
+            U256::from(100u64),
+            U256::ZERO,
+            U256::from(1u64),
+            this,
+            U256::from(500u64),
+            U256::from(2_000u64),
+            U256::from(200u64),
+        );
+        assert_eq!(base, repeated);
+    }
+
+    /// Regresión: un registro emitido 1 segundo antes de un límite de
+    /// epoch y podado 1 segundo después de ese mismo límite tiene ~2
+    /// segundos de antigüedad real, no 5 años. Comparar por bucket de
+    /// `rent_epoch` lo marcaría podable igual; la poda debe basarse en el
+    /// tiempo transcurrido real (`timestamp_issued` vs. la ventana de 5
+    /// años), usando `rent_epoch` solo como índice, nunca como cutoff.
+    #[test]
+    fn rent_epoch_bucket_is_not_a_valid_prune_cutoff() {
+        let boundary = FIVE_YEARS_IN_SECONDS;
+        let timestamp_issued = boundary - U256::from(1u64);
+        let now = boundary + U256::from(1u64);
+
+        let issued_epoch = LoanComplianceLedger::rent_epoch_for(timestamp_issued);
+        let current_epoch = LoanComplianceLedger::rent_epoch_for(now);
+        assert!(
+            issued_epoch < current_epoch,
+            "el registro cruza un límite de epoch aunque casi no pasó tiempo real"
+        );
+
+        let five_years_ago = now.saturating_sub(FIVE_YEARS_IN_SECONDS);
+        assert!(
+            timestamp_issued >= five_years_ago,
+            "el cutoff por tiempo real no debe considerar podable un registro de ~2 segundos"
+        );
+    }
+
+    /// `interest_rate_bps` lo fija el banco (`add_loan_record_signed` no le
+    /// pone tope) y un préstamo puede vivir años: el producto
+    /// `loan_amount * interest_rate_bps * elapsed` se desborda en U256
+    /// mucho antes que en U512. `accrued_interest` debe saturar en vez de
+    /// envolver en silencio.
+    #[test]
+    fn accrued_interest_saturates_instead_of_wrapping() {
+        let loan_amount = U256::MAX / U256::from(2u64);
+        let interest_rate_bps = U256::from(30_000u64); // 300%
+        let timestamp_issued = U256::ZERO;
+        let now = SECONDS_PER_YEAR; // un año exacto transcurrido
+
+        let accrued = LoanComplianceLedger::accrued_interest(
+            loan_amount,
+            interest_rate_bps,
+            timestamp_issued,
+            now,
+        );
+
+        // Sin el ensanchado a U512 este producto se desbordaría y
+        // envolvería a un valor pequeño o inesperado. El interés real
+        // (3 * (U256::MAX / 2)) no cabe en U256, así que satura en U256::MAX.
+        assert_eq!(accrued, U256::MAX);
+    }
+
+    #[test]
+    fn accrued_interest_is_zero_with_no_elapsed_time() {
+        let accrued = LoanComplianceLedger::accrued_interest(
+            U256::from(1_000u64),
+            U256::from(500u64),
+            U256::from(100u64),
+            U256::from(100u64),
+        );
+        assert_eq!(accrued, U256::ZERO);
+    }
 }
\ No newline at end of file